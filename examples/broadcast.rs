@@ -1,11 +1,11 @@
 use std::net::{TcpListener, TcpStream};
 use std::thread;
 
-use autodiscover_rs::{self, Method};
+use autodiscover_rs::{self, Config, Metadata, Method};
 use env_logger;
 
-fn handle_client(stream: std::io::Result<TcpStream>) {
-    println!("Got a connection from {:?}", stream.unwrap().peer_addr());
+fn handle_client(stream: std::io::Result<TcpStream>, metadata: Metadata) {
+    println!("Got a connection from {:?}, metadata {:?}", stream.unwrap().peer_addr(), metadata);
 }
 
 fn main() -> std::io::Result<()> {
@@ -14,14 +14,14 @@ fn main() -> std::io::Result<()> {
     let listener = TcpListener::bind("0.0.0.0:0")?;
     let socket = listener.local_addr()?;
     thread::spawn(move || {
-            autodiscover_rs::run(&socket, Method::Broadcast("255.255.255.255:2020".parse().unwrap()), |s| {
+            autodiscover_rs::run(&socket, Method::Broadcast("255.255.255.255:2020".parse().unwrap()), Config::new(), |s, metadata| {
                 // change this to be async if using tokio or async_std
-                thread::spawn(|| handle_client(s));
+                thread::spawn(move || handle_client(s, metadata));
         }).unwrap();
     });
     let mut incoming = listener.incoming();
     while let Some(stream) = incoming.next() {
-        thread::spawn(|| handle_client(stream));
+        thread::spawn(|| handle_client(stream, Metadata::default()));
     }
     Ok(())
-}
\ No newline at end of file
+}
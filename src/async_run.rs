@@ -0,0 +1,196 @@
+//! Async equivalents of [`run`](crate::run), so callers don't have to burn a dedicated thread on
+//! the discovery loop. Pick the `tokio` or `async-std` feature depending on your runtime; both
+//! expose the same `run_async` entry point and follow the same announce-then-listen sequence and
+//! self-connection suppression as the sync path.
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use socket2::{Socket, Domain, Type};
+use log::warn;
+use futures::future::{select, Either};
+use futures::pin_mut;
+
+use crate::{wire, Config, Method, Metadata};
+
+#[cfg(feature = "tokio")]
+mod rt {
+    pub use tokio::net::{TcpStream, UdpSocket};
+    pub async fn connect(addr: std::net::SocketAddr) -> std::io::Result<TcpStream> {
+        TcpStream::connect(addr).await
+    }
+    // tokio's UdpSocket::from_std is fallible (it also registers the socket with tokio's reactor).
+    pub fn from_std(socket: std::net::UdpSocket) -> std::io::Result<UdpSocket> {
+        UdpSocket::from_std(socket)
+    }
+    pub async fn sleep(duration: std::time::Duration) {
+        tokio::time::sleep(duration).await
+    }
+}
+
+#[cfg(feature = "async-std")]
+mod rt {
+    pub use async_std::net::{TcpStream, UdpSocket};
+    pub async fn connect(addr: std::net::SocketAddr) -> std::io::Result<TcpStream> {
+        TcpStream::connect(addr).await
+    }
+    // unlike tokio, async-std's UdpSocket only offers an infallible `From<std::net::UdpSocket>`.
+    pub fn from_std(socket: std::net::UdpSocket) -> std::io::Result<UdpSocket> {
+        Ok(UdpSocket::from(socket))
+    }
+    pub async fn sleep(duration: std::time::Duration) {
+        async_std::task::sleep(duration).await
+    }
+}
+
+use rt::{TcpStream, UdpSocket};
+
+fn bind_broadcast(addr: SocketAddr) -> std::io::Result<std::net::UdpSocket> {
+    let socket = Socket::new(Domain::ipv4(), Type::dgram(), None)?;
+    socket.set_reuse_address(true)?;
+    socket.set_broadcast(true)?;
+    socket.bind(&addr.into())?;
+    socket.set_nonblocking(true)?;
+    Ok(socket.into_udp_socket())
+}
+
+// Keep this in lockstep with the sync `run`'s multicast binding in lib.rs: any `Config` field that
+// changes join/TTL/loopback behavior there needs the same change here, in the same commit, since
+// this is the only other place that applies a `Config` to a multicast socket (the mDNS path in
+// mdns.rs joins its own group without one).
+fn bind_multicast(addr: SocketAddr, config: &Config) -> std::io::Result<std::net::UdpSocket> {
+    let domain = if addr.is_ipv4() { Domain::ipv4() } else { Domain::ipv6() };
+    let socket = Socket::new(domain, Type::dgram(), None)?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    match addr.ip() {
+        IpAddr::V4(group) => {
+            let iface = config.interface_v4.unwrap_or(Ipv4Addr::UNSPECIFIED);
+            socket.join_multicast_v4(&group, &iface)?;
+            socket.set_multicast_if_v4(&iface)?;
+            if let Some(ttl) = config.ttl_v4 {
+                socket.set_multicast_ttl_v4(ttl)?;
+            }
+            if let Some(enabled) = config.loop_v4 {
+                socket.set_multicast_loop_v4(enabled)?;
+            }
+        },
+        IpAddr::V6(group) => {
+            let iface_index = config.interface_v6_index.unwrap_or(0);
+            socket.join_multicast_v6(&group, iface_index)?;
+            socket.set_multicast_if_v6(iface_index)?;
+            if let Some(hops) = config.hops_v6 {
+                socket.set_multicast_hops_v6(hops)?;
+            }
+            if let Some(enabled) = config.loop_v6 {
+                socket.set_multicast_loop_v6(enabled)?;
+            }
+        },
+    }
+    socket.set_nonblocking(true)?;
+    Ok(socket.into_udp_socket())
+}
+
+/// If set, re-announce `payload` to `addr` every `interval`, doubling (up to `cap`, if any) after
+/// each send, interleaved with the receive loop via `select` so it never blocks receiving.
+struct Reannounce {
+    addr: SocketAddr,
+    payload: Vec<u8>,
+    interval: Duration,
+    cap: Option<Duration>,
+}
+
+async fn handle_broadcast_message_async<F, Fut>(
+    socket: UdpSocket,
+    my_socket: &SocketAddr,
+    cluster_id: u16,
+    secret: Option<&[u8]>,
+    mut reannounce: Option<Reannounce>,
+    callback: &F,
+) -> std::io::Result<()>
+where
+    F: Fn(std::io::Result<TcpStream>, Metadata) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let mut buff = vec![0; 512];
+    let mut timer: Pin<Box<dyn Future<Output = ()> + Send>> = match &reannounce {
+        Some(r) => Box::pin(rt::sleep(r.interval)),
+        None => Box::pin(futures::future::pending()),
+    };
+    loop {
+        let recv_fut = socket.recv_from(&mut buff);
+        pin_mut!(recv_fut);
+        match select(recv_fut, timer).await {
+            Either::Left((result, pending_timer)) => {
+                timer = pending_timer;
+                let (bytes, _) = result?;
+                if let Ok((addr, metadata)) = wire::decode(cluster_id, secret, &buff[..bytes]) {
+                    if addr == *my_socket {
+                        continue;
+                    }
+                    let stream = rt::connect(addr).await;
+                    callback(stream, metadata).await;
+                }
+            },
+            Either::Right((_, _)) => {
+                let r = reannounce.as_mut().expect("timer only fires when reannounce is set");
+                if let Err(e) = socket.send_to(&r.payload, r.addr).await {
+                    warn!("failed to re-announce: {}", e);
+                }
+                if let Some(cap) = r.cap {
+                    r.interval = std::cmp::min(r.interval * 2, cap);
+                }
+                timer = Box::pin(rt::sleep(r.interval));
+            },
+        }
+    }
+}
+
+/// Async version of [`run`](crate::run). Requires the `tokio` or `async-std` feature. `spawn_callback`
+/// is awaited in place, so if you want concurrent handling of connections, `task::spawn` inside it
+/// rather than blocking. `Config::with_reannounce`/`with_reannounce_backoff_cap` are honored here too,
+/// via a timer interleaved with the receive loop rather than a second thread. Only `Method::Broadcast`
+/// and `Method::Multicast` are handled; `Method::MdnsService` returns an `ErrorKind::Unsupported` error.
+pub async fn run_async<F, Fut>(connect_to: &SocketAddr, method: Method, config: Config, spawn_callback: F) -> std::io::Result<()>
+where
+    F: Fn(std::io::Result<TcpStream>, Metadata) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    match method {
+        Method::Broadcast(addr) => {
+            let socket = bind_broadcast(addr)?;
+            let socket = rt::from_std(socket)?;
+            let frame = wire::encode(config.cluster_id, connect_to, &config.metadata, config.secret.as_deref());
+            socket.send_to(&frame, addr).await?;
+            let reannounce = config.reannounce_interval.map(|interval| Reannounce {
+                addr,
+                payload: frame.clone(),
+                interval,
+                cap: config.reannounce_backoff_cap,
+            });
+            handle_broadcast_message_async(socket, connect_to, config.cluster_id, config.secret.as_deref(), reannounce, &spawn_callback).await?;
+        },
+        Method::Multicast(addr) => {
+            let socket = bind_multicast(addr, &config)?;
+            let socket = rt::from_std(socket)?;
+            let frame = wire::encode(config.cluster_id, connect_to, &config.metadata, config.secret.as_deref());
+            socket.send_to(&frame, addr).await?;
+            let reannounce = config.reannounce_interval.map(|interval| Reannounce {
+                addr,
+                payload: frame.clone(),
+                interval,
+                cap: config.reannounce_backoff_cap,
+            });
+            handle_broadcast_message_async(socket, connect_to, config.cluster_id, config.secret.as_deref(), reannounce, &spawn_callback).await?;
+        },
+        Method::MdnsService { .. } => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "Method::MdnsService is not yet supported by run_async",
+            ));
+        },
+    }
+    warn!("It looks like I stopped listening; this shouldn't happen.");
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use crate::Metadata;
+
+/// Config controls the behavior of [`run`](crate::run)'s `Broadcast`/`Multicast` methods: the
+/// multicast interface/TTL/loopback settings, the periodic re-announce schedule, and the cluster
+/// ID/metadata we announce ourselves with. All fields default to the previous hardcoded behavior
+/// (OS default interface/TTL/loopback, announce once, cluster ID 0, no metadata).
+///
+/// Build one with [`Config::new`] and the `with_*` methods, e.g.:
+///
+/// ```
+/// use std::net::Ipv4Addr;
+/// use autodiscover_rs::Config;
+///
+/// let config = Config::new()
+///     .with_interface_v4(Ipv4Addr::new(192, 168, 1, 10))
+///     .with_ttl_v4(4);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    pub(crate) interface_v4: Option<Ipv4Addr>,
+    pub(crate) interface_v6_index: Option<u32>,
+    pub(crate) ttl_v4: Option<u32>,
+    pub(crate) hops_v6: Option<u32>,
+    pub(crate) loop_v4: Option<bool>,
+    pub(crate) loop_v6: Option<bool>,
+    pub(crate) reannounce_interval: Option<Duration>,
+    pub(crate) reannounce_backoff_cap: Option<Duration>,
+    pub(crate) cluster_id: u16,
+    pub(crate) metadata: Metadata,
+    pub(crate) secret: Option<Vec<u8>>,
+}
+
+impl Config {
+    /// Creates a `Config` with every setting left at the OS default.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sends and joins IPv4 multicast traffic through the given interface instead of the default one.
+    pub fn with_interface_v4(mut self, interface: Ipv4Addr) -> Self {
+        self.interface_v4 = Some(interface);
+        self
+    }
+
+    /// Sends and joins IPv6 multicast traffic through the interface with the given index, instead
+    /// of letting the OS pick one.
+    pub fn with_interface_v6(mut self, interface_index: u32) -> Self {
+        self.interface_v6_index = Some(interface_index);
+        self
+    }
+
+    /// Sets the outbound IPv4 multicast TTL (default is usually 1, restricting traffic to the local subnet).
+    pub fn with_ttl_v4(mut self, ttl: u32) -> Self {
+        self.ttl_v4 = Some(ttl);
+        self
+    }
+
+    /// Sets the outbound IPv6 multicast hop limit (the IPv6 equivalent of TTL).
+    pub fn with_hops_v6(mut self, hops: u32) -> Self {
+        self.hops_v6 = Some(hops);
+        self
+    }
+
+    /// Enables or disables IPv4 multicast loopback, i.e. whether our own announcements are delivered
+    /// back to us on this host.
+    pub fn with_loop_v4(mut self, enabled: bool) -> Self {
+        self.loop_v4 = Some(enabled);
+        self
+    }
+
+    /// Enables or disables IPv6 multicast loopback, i.e. whether our own announcements are delivered
+    /// back to us on this host.
+    pub fn with_loop_v6(mut self, enabled: bool) -> Self {
+        self.loop_v6 = Some(enabled);
+        self
+    }
+
+    /// Enables periodic re-announcement: besides the initial announcement, `run` resends it every
+    /// `interval` for as long as it's running, without blocking the receive loop. This helps peers
+    /// that start up slightly late, or that drop the single initial datagram (UDP delivery isn't
+    /// guaranteed), converge on the full peer set. Disabled by default, i.e. `run` announces once.
+    pub fn with_reannounce(mut self, interval: Duration) -> Self {
+        self.reannounce_interval = Some(interval);
+        self
+    }
+
+    /// Doubles the re-announce interval after each send, up to `cap`, instead of resending at a
+    /// fixed interval forever. Has no effect unless [`Config::with_reannounce`] is also set.
+    pub fn with_reannounce_backoff_cap(mut self, cap: Duration) -> Self {
+        self.reannounce_backoff_cap = Some(cap);
+        self
+    }
+
+    /// Sets the cluster/application ID we announce and require from peers. Frames whose cluster ID
+    /// doesn't match are ignored, so two unrelated applications sharing a multicast group won't try
+    /// to connect to each other. Defaults to 0; any two applications that want to interoperate need
+    /// to agree on the same ID out of band.
+    pub fn with_cluster_id(mut self, cluster_id: u16) -> Self {
+        self.cluster_id = cluster_id;
+        self
+    }
+
+    /// Sets the [`Metadata`] (node UUID, name, capabilities) we announce about ourselves. Empty by default.
+    pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Enables HMAC authentication of discovery datagrams using `secret` as the shared key: our
+    /// announcements are tagged with an HMAC-SHA256 and a timestamp, and we only connect to peers
+    /// whose announcements carry a valid tag under the same secret and a timestamp within 30 seconds
+    /// of our clock; a replayed announcement inside that window is still accepted. Disabled
+    /// by default, in which case we send and accept only unauthenticated frames, as before this
+    /// option existed. A rogue host without the secret can no longer make us dial arbitrary addresses.
+    pub fn with_secret(mut self, secret: impl Into<Vec<u8>>) -> Self {
+        self.secret = Some(secret.into());
+        self
+    }
+}
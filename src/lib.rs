@@ -5,11 +5,11 @@
 //! ```rust,no_run
 //! use std::net::{TcpListener, TcpStream};
 //! use std::thread;
-//! use autodiscover_rs::Method;
+//! use autodiscover_rs::{Method, Metadata};
 //! use env_logger;
 //!
-//! fn handle_client(stream: std::io::Result<TcpStream>) {
-//!     println!("Got a connection from {:?}", stream.unwrap().peer_addr());
+//! fn handle_client(stream: std::io::Result<TcpStream>, metadata: Metadata) {
+//!     println!("Got a connection from {:?}, metadata {:?}", stream.unwrap().peer_addr(), metadata);
 //! }
 //!
 //! fn main() -> std::io::Result<()> {
@@ -20,21 +20,20 @@
 //!     let socket = listener.local_addr()?;
 //!     thread::spawn(move || {
 //!         // this function blocks forever; running it a seperate thread
-//!         autodiscover_rs::run(&socket, Method::Multicast("[ff0e::1]:1337".parse().unwrap()), |s| {
+//!         autodiscover_rs::run(&socket, Method::Multicast("[ff0e::1]:1337".parse().unwrap()), autodiscover_rs::Config::new(), |s, metadata| {
 //!             // change this to task::spawn if using async_std or tokio
-//!             thread::spawn(|| handle_client(s));
+//!             thread::spawn(move || handle_client(s, metadata));
 //!         }).unwrap();
 //!     });
 //!     let mut incoming = listener.incoming();
 //!     while let Some(stream) = incoming.next() {
 //!         // if you are using an async library, such as async_std or tokio, you can convert the stream to the
 //!         // appropriate type before using task::spawn from your library of choice.
-//!         thread::spawn(|| handle_client(stream));
+//!         thread::spawn(|| handle_client(stream, Metadata::default()));
 //!     }
 //!     Ok(())
 //! }
 //! ```
-use std::convert::TryInto;
 use std::net::{
     IpAddr,
     SocketAddr,
@@ -42,9 +41,20 @@ use std::net::{
     UdpSocket,
     Ipv4Addr,
 };
+use std::thread;
 use socket2::{Socket, Domain, Type};
 use log::{trace, warn};
 
+mod config;
+mod mdns;
+mod wire;
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+mod async_run;
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+pub use async_run::run_async;
+pub use config::Config;
+pub use wire::Metadata;
+
 /// Method describes whether a multicast or broadcast method for sending discovery messages should be used.
 pub enum Method {
     /// Broadcast is an IPv4-only method of sending discovery messages; use a value such as `"255.255.255.255:1337".parse()` or
@@ -53,67 +63,61 @@ pub enum Method {
     /// Multicast supports both IPv6 and IPv4 for sending discovery methods; use a value such as `"224.0.0.1".parse()` for IPv4, or
     /// `"[ff0e::1]:1337".parse()` for IPv6. To be frank, IPv6 confuses me, but that address worked on my machine.
     Multicast(SocketAddr),
+    /// MdnsService discovers peers via standard zero-conf multicast DNS (mDNS/DNS-SD), the same mechanism used by
+    /// Bonjour/Avahi. `service_type` should look like `"_myapp._tcp.local"` and `instance` is this node's unique name
+    /// within that service type, e.g. `"my-node-1"`. Use this instead of `Broadcast`/`Multicast` when you want
+    /// `autodiscover-rs` nodes to be discoverable by (or discover) other zero-conf tooling.
+    MdnsService { service_type: String, instance: String },
+}
+
+/// Spawns a background thread that resends `payload` to `addr` every `config.reannounce_interval`,
+/// doubling the interval after each send up to `config.reannounce_backoff_cap` if one is set. Does
+/// nothing if `config.reannounce_interval` is unset. Runs on a clone of `socket` so it never blocks
+/// the caller's receive loop.
+fn spawn_reannounce(socket: &UdpSocket, addr: SocketAddr, payload: Vec<u8>, config: &Config) -> std::io::Result<()> {
+    let interval = match config.reannounce_interval {
+        Some(interval) => interval,
+        None => return Ok(()),
+    };
+    let cap = config.reannounce_backoff_cap;
+    let socket = socket.try_clone()?;
+    thread::spawn(move || {
+        let mut interval = interval;
+        loop {
+            thread::sleep(interval);
+            if let Err(e) = socket.send_to(&payload, addr) {
+                warn!("failed to re-announce: {}", e);
+            }
+            if let Some(cap) = cap {
+                interval = std::cmp::min(interval * 2, cap);
+            }
+        }
+    });
+    Ok(())
 }
 
-fn handle_broadcast_message<F: Fn(std::io::Result<TcpStream>)>(socket: UdpSocket, my_socket: &SocketAddr, callback: &F) -> std::io::Result<()> {
-    let mut buff = vec![0; 18];
+fn handle_broadcast_message<F: Fn(std::io::Result<TcpStream>, Metadata)>(socket: UdpSocket, my_socket: &SocketAddr, cluster_id: u16, secret: Option<&[u8]>, callback: &F) -> std::io::Result<()> {
+    let mut buff = vec![0; 512];
     loop {
         let (bytes, _) = socket.recv_from(&mut buff)?;
-        if let Ok(socket) = parse_bytes(bytes, &buff) {
-            if socket == *my_socket {
+        if let Ok((addr, metadata)) = wire::decode(cluster_id, secret, &buff[..bytes]) {
+            if addr == *my_socket {
                 trace!("saw connection attempt from myself, this should happen once");
                 continue;
             }
-            let stream = TcpStream::connect(socket);
-            callback(stream);
-        }
-    }
-}
-
-fn parse_bytes(len: usize, buff: &[u8]) -> Result<SocketAddr, ()> {
-    let addr = match len {
-        6 => {
-            let ip = IpAddr::V4(u32::from_be_bytes(buff[0..4].try_into().unwrap()).into());
-            let port = u16::from_be_bytes(buff[4..6].try_into().unwrap());
-            SocketAddr::new(ip, port)
-        },
-        18 => {
-            let ip: [u8; 16] = buff[0..16].try_into().unwrap();
-            let ip = ip.into();
-            let port = u16::from_be_bytes(buff[16..18].try_into().unwrap());
-            SocketAddr::new(ip, port)
-        },
-        _ => {
-            warn!("Dropping malformed packet; length was {}", len);
-            return Err(())
-        },
-    };
-    Ok(addr)
-}
-
-fn to_bytes(connect_to: &SocketAddr) -> Vec<u8> {
-    match connect_to {
-        SocketAddr::V6(addr) => {
-            // length is 16 bytes + 2 bytes
-            let mut buff = vec![0; 18];
-            buff[0..16].clone_from_slice(&addr.ip().octets());
-            buff[16..18].clone_from_slice(&addr.port().to_be_bytes());
-            buff
-        },
-        SocketAddr::V4(addr) => {
-            // length is 4 bytes + 2 bytes
-            let mut buff = vec![0; 6];
-            buff[0..4].clone_from_slice(&addr.ip().octets());
-            buff[4..6].clone_from_slice(&addr.port().to_be_bytes());
-            buff
+            let stream = TcpStream::connect(addr);
+            callback(stream, metadata);
         }
     }
 }
 
 /// run will block forever. It sends a notification using the configured method, then listens for other notifications and begins
-/// connecting to them, calling spawn_callback (which should return right away!) with the connected streams. The connect_to address
-/// should be a socket we have already bind'ed too, since we advertise that to other autodiscovery clients.
-pub fn run<F: Fn(std::io::Result<TcpStream>)>(connect_to: &SocketAddr, method: Method, spawn_callback: F) -> std::io::Result<()> {
+/// connecting to them, calling spawn_callback (which should return right away!) with the connected streams and the peer's announced
+/// metadata. The connect_to address should be a socket we have already bind'ed too, since we advertise that to other autodiscovery
+/// clients. config lets you tune the `Multicast` interface/TTL/loopback settings, schedule periodic re-announcements via
+/// `Config::with_reannounce`, and set the cluster ID/metadata we announce ourselves with; pass `Config::new()` to keep the previous
+/// (announce-once, OS default, cluster ID 0) behavior.
+pub fn run<F: Fn(std::io::Result<TcpStream>, Metadata)>(connect_to: &SocketAddr, method: Method, config: Config, spawn_callback: F) -> std::io::Result<()> {
     match method {
         Method::Broadcast(addr) => {
             let socket = Socket::new(Domain::ipv4(), Type::dgram(), None)?;
@@ -121,30 +125,51 @@ pub fn run<F: Fn(std::io::Result<TcpStream>)>(connect_to: &SocketAddr, method: M
             socket.set_broadcast(true)?;
             socket.bind(&addr.into())?;
             let socket: UdpSocket = socket.into_udp_socket();
-            socket.send_to(&to_bytes(connect_to), addr)?;
-            handle_broadcast_message(socket, connect_to, &spawn_callback)?;
+            let frame = wire::encode(config.cluster_id, connect_to, &config.metadata, config.secret.as_deref());
+            socket.send_to(&frame, addr)?;
+            spawn_reannounce(&socket, addr, frame.clone(), &config)?;
+            handle_broadcast_message(socket, connect_to, config.cluster_id, config.secret.as_deref(), &spawn_callback)?;
         },
         Method::Multicast(addr) => {
-            let socket = Socket::new(Domain::ipv6(), Type::dgram(), None)?;
+            // the socket's domain must match the multicast group's address family, or joining/sending fails on most platforms
+            let domain = if addr.is_ipv4() { Domain::ipv4() } else { Domain::ipv6() };
+            let socket = Socket::new(domain, Type::dgram(), None)?;
             socket.set_reuse_address(true)?;
             socket.bind(&addr.into())?;
-            let socket: UdpSocket = socket.into_udp_socket();
             match addr.ip() {
-                IpAddr::V4(addr) => {
-                    let iface: Ipv4Addr = 0u32.into();
-                    socket.join_multicast_v4(&addr, &iface)?;
+                IpAddr::V4(group) => {
+                    let iface = config.interface_v4.unwrap_or(Ipv4Addr::UNSPECIFIED);
+                    socket.join_multicast_v4(&group, &iface)?;
+                    socket.set_multicast_if_v4(&iface)?;
+                    if let Some(ttl) = config.ttl_v4 {
+                        socket.set_multicast_ttl_v4(ttl)?;
+                    }
+                    if let Some(enabled) = config.loop_v4 {
+                        socket.set_multicast_loop_v4(enabled)?;
+                    }
                 },
-                IpAddr::V6(addr) => {
-                    socket.join_multicast_v6(&addr, 0)?;
+                IpAddr::V6(group) => {
+                    let iface_index = config.interface_v6_index.unwrap_or(0);
+                    socket.join_multicast_v6(&group, iface_index)?;
+                    socket.set_multicast_if_v6(iface_index)?;
+                    if let Some(hops) = config.hops_v6 {
+                        socket.set_multicast_hops_v6(hops)?;
+                    }
+                    if let Some(enabled) = config.loop_v6 {
+                        socket.set_multicast_loop_v6(enabled)?;
+                    }
                 },
             }
-            // we need a different, temporary socket, to send multicast in IPv6
-            {
-                let socket = UdpSocket::bind(":::0")?;
-                let result = socket.send_to(&to_bytes(connect_to), addr)?;
-                warn!("sent {} bytes to {:?}", result, addr);
-            }
-            handle_broadcast_message(socket, connect_to, &spawn_callback)?;
+            // send from the same, already-bound-and-scoped socket we listen on, rather than a throwaway one
+            let socket: UdpSocket = socket.into_udp_socket();
+            let frame = wire::encode(config.cluster_id, connect_to, &config.metadata, config.secret.as_deref());
+            socket.send_to(&frame, addr)?;
+            spawn_reannounce(&socket, addr, frame.clone(), &config)?;
+            handle_broadcast_message(socket, connect_to, config.cluster_id, config.secret.as_deref(), &spawn_callback)?;
+        },
+        Method::MdnsService { service_type, instance } => {
+            // the mDNS path uses standard DNS-SD records rather than our framed format, so it has no metadata to report
+            mdns::run(connect_to, &service_type, &instance, &|stream| spawn_callback(stream, Metadata::default()))?;
         },
     }
     warn!("It looks like I stopped listening; this shouldn't happen.");
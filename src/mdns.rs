@@ -0,0 +1,324 @@
+//! A minimal mDNS / DNS-SD announcer and resolver.
+//!
+//! This is not a general-purpose DNS library: it knows just enough of the wire format to
+//! announce a PTR+SRV+A/AAAA record set for one service instance and to pick matching SRV/A/AAAA
+//! records back out of whatever else shows up on the mDNS multicast group. Outgoing messages are
+//! not name-compressed; incoming messages may be, so decoding understands compression pointers.
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket};
+
+use socket2::{Domain, Socket, Type};
+use log::{trace, warn};
+
+/// The well-known mDNS multicast group and port, IPv4 and IPv6.
+pub(crate) const MDNS_ADDR_V4: (Ipv4Addr, u16) = (Ipv4Addr::new(224, 0, 0, 251), 5353);
+pub(crate) const MDNS_ADDR_V6: (Ipv6Addr, u16) = (Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 0xfb), 5353);
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_AAAA: u16 = 28;
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+
+fn encode_name(out: &mut Vec<u8>, name: &str) {
+    for label in name.trim_end_matches('.').split('.') {
+        out.push(label.len() as u8);
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(0);
+}
+
+/// Builds a DNS response message announcing `instance.service_type` at `port`, via a PTR record
+/// (service_type -> instance), an SRV record (instance -> host, port) and an A/AAAA record
+/// (host -> connect_to's address).
+fn build_announce(service_type: &str, instance: &str, connect_to: &SocketAddr) -> Vec<u8> {
+    let owner = format!("{}.{}", instance, service_type);
+    let host = format!("{}.local", instance);
+
+    let mut msg = Vec::new();
+    // header: id, flags (response, authoritative), qdcount, ancount, nscount, arcount
+    msg.extend_from_slice(&0u16.to_be_bytes());
+    msg.extend_from_slice(&0x8400u16.to_be_bytes());
+    msg.extend_from_slice(&0u16.to_be_bytes());
+    msg.extend_from_slice(&3u16.to_be_bytes());
+    msg.extend_from_slice(&0u16.to_be_bytes());
+    msg.extend_from_slice(&0u16.to_be_bytes());
+
+    // PTR: service_type -> owner
+    encode_name(&mut msg, service_type);
+    msg.extend_from_slice(&TYPE_PTR.to_be_bytes());
+    msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+    msg.extend_from_slice(&120u32.to_be_bytes());
+    let mut rdata = Vec::new();
+    encode_name(&mut rdata, &owner);
+    msg.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+    msg.extend_from_slice(&rdata);
+
+    // SRV: owner -> host:port
+    encode_name(&mut msg, &owner);
+    msg.extend_from_slice(&TYPE_SRV.to_be_bytes());
+    msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+    msg.extend_from_slice(&120u32.to_be_bytes());
+    let mut rdata = Vec::new();
+    rdata.extend_from_slice(&0u16.to_be_bytes()); // priority
+    rdata.extend_from_slice(&0u16.to_be_bytes()); // weight
+    rdata.extend_from_slice(&connect_to.port().to_be_bytes());
+    encode_name(&mut rdata, &host);
+    msg.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+    msg.extend_from_slice(&rdata);
+
+    // A/AAAA: host -> address
+    encode_name(&mut msg, &host);
+    match connect_to.ip() {
+        IpAddr::V4(ip) => {
+            msg.extend_from_slice(&TYPE_A.to_be_bytes());
+            msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+            msg.extend_from_slice(&120u32.to_be_bytes());
+            msg.extend_from_slice(&4u16.to_be_bytes());
+            msg.extend_from_slice(&ip.octets());
+        },
+        IpAddr::V6(ip) => {
+            msg.extend_from_slice(&TYPE_AAAA.to_be_bytes());
+            msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+            msg.extend_from_slice(&120u32.to_be_bytes());
+            msg.extend_from_slice(&16u16.to_be_bytes());
+            msg.extend_from_slice(&ip.octets());
+        },
+    }
+    msg
+}
+
+/// Reads a (possibly compressed) DNS name starting at `offset`, returning the decoded name and
+/// the offset immediately after it in the original message (not following any pointer).
+fn decode_name(msg: &[u8], mut offset: usize) -> Result<(String, usize), ()> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    let mut hops = 0;
+    loop {
+        hops += 1;
+        if hops > 128 {
+            // bail out on a pointer loop rather than spinning forever
+            return Err(());
+        }
+        let len = *msg.get(offset).ok_or(())?;
+        if len == 0 {
+            offset += 1;
+            break;
+        } else if len & 0xc0 == 0xc0 {
+            let lo = *msg.get(offset + 1).ok_or(())?;
+            let pointer = (((len & 0x3f) as usize) << 8) | lo as usize;
+            if end.is_none() {
+                end = Some(offset + 2);
+            }
+            offset = pointer;
+        } else {
+            let len = len as usize;
+            let start = offset + 1;
+            let label = msg.get(start..start + len).ok_or(())?;
+            labels.push(String::from_utf8_lossy(label).into_owned());
+            offset = start + len;
+        }
+    }
+    Ok((labels.join("."), end.unwrap_or(offset)))
+}
+
+struct Record {
+    name: String,
+    rtype: u16,
+    rdata_start: usize,
+    rdata_len: usize,
+}
+
+fn parse_records(msg: &[u8]) -> Result<Vec<Record>, ()> {
+    if msg.len() < 12 {
+        return Err(());
+    }
+    let qdcount = u16::from_be_bytes(msg[4..6].try_into().unwrap());
+    let ancount = u16::from_be_bytes(msg[6..8].try_into().unwrap());
+    let mut offset = 12;
+    for _ in 0..qdcount {
+        let (_, next) = decode_name(msg, offset)?;
+        offset = next + 4; // qtype + qclass
+    }
+    let mut records = Vec::new();
+    for _ in 0..ancount {
+        let (name, next) = decode_name(msg, offset)?;
+        let rtype = u16::from_be_bytes(msg.get(next..next + 2).ok_or(())?.try_into().unwrap());
+        let rdlength = u16::from_be_bytes(msg.get(next + 8..next + 10).ok_or(())?.try_into().unwrap()) as usize;
+        let rdata_start = next + 10;
+        if rdata_start + rdlength > msg.len() {
+            return Err(());
+        }
+        records.push(Record { name, rtype, rdata_start, rdata_len: rdlength });
+        offset = rdata_start + rdlength;
+    }
+    Ok(records)
+}
+
+/// Pulls out SRV targets (owner -> (host, port)) and A/AAAA addresses (host -> ip) for records
+/// whose owner name belongs to `service_type`, then resolves each SRV target to a `SocketAddr`.
+fn resolve_instances(msg: &[u8], service_type: &str) -> Vec<SocketAddr> {
+    let records = match parse_records(msg) {
+        Ok(records) => records,
+        Err(()) => {
+            warn!("dropping malformed mDNS message");
+            return Vec::new();
+        },
+    };
+    let mut addrs: HashMap<String, IpAddr> = HashMap::new();
+    let mut srvs: Vec<(String, u16)> = Vec::new();
+    for record in &records {
+        match record.rtype {
+            TYPE_A if record.rdata_len == 4 => {
+                let ip = Ipv4Addr::new(
+                    msg[record.rdata_start],
+                    msg[record.rdata_start + 1],
+                    msg[record.rdata_start + 2],
+                    msg[record.rdata_start + 3],
+                );
+                addrs.insert(record.name.clone(), IpAddr::V4(ip));
+            },
+            TYPE_AAAA if record.rdata_len == 16 => {
+                let octets: [u8; 16] = msg[record.rdata_start..record.rdata_start + 16].try_into().unwrap();
+                addrs.insert(record.name.clone(), IpAddr::V6(octets.into()));
+            },
+            TYPE_SRV if record.rdata_len >= 6 && record.name.ends_with(service_type) => {
+                let port_bytes = match msg.get(record.rdata_start + 4..record.rdata_start + 6) {
+                    Some(bytes) => bytes,
+                    None => continue,
+                };
+                let port = u16::from_be_bytes(port_bytes.try_into().unwrap());
+                if let Ok((target, _)) = decode_name(msg, record.rdata_start + 6) {
+                    srvs.push((target, port));
+                }
+            },
+            _ => {},
+        }
+    }
+    srvs.into_iter()
+        .filter_map(|(target, port)| addrs.get(&target).map(|ip| SocketAddr::new(*ip, port)))
+        .collect()
+}
+
+fn bind_socket(v4: bool) -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(if v4 { Domain::ipv4() } else { Domain::ipv6() }, Type::dgram(), None)?;
+    socket.set_reuse_address(true)?;
+    let bind_addr: SocketAddr = if v4 { "0.0.0.0:5353".parse().unwrap() } else { ":::5353".parse().unwrap() };
+    socket.bind(&bind_addr.into())?;
+    Ok(socket.into_udp_socket())
+}
+
+/// Joins the mDNS group for `connect_to`'s address family, announces `instance.service_type`
+/// pointing at `connect_to`, then listens for other instances and connects to each one found,
+/// exactly as `handle_broadcast_message` does for the ad-hoc broadcast/multicast methods.
+pub(crate) fn run<F: Fn(std::io::Result<TcpStream>)>(
+    connect_to: &SocketAddr,
+    service_type: &str,
+    instance: &str,
+    spawn_callback: &F,
+) -> std::io::Result<()> {
+    let v4 = connect_to.is_ipv4();
+    let socket = bind_socket(v4)?;
+    let group_addr: SocketAddr = if v4 {
+        let (ip, port) = MDNS_ADDR_V4;
+        socket.join_multicast_v4(&ip, &Ipv4Addr::UNSPECIFIED)?;
+        (ip, port).into()
+    } else {
+        let (ip, port) = MDNS_ADDR_V6;
+        socket.join_multicast_v6(&ip, 0)?;
+        (ip, port).into()
+    };
+
+    let announce = build_announce(service_type, instance, connect_to);
+    socket.send_to(&announce, group_addr)?;
+
+    let mut buff = vec![0; 4096];
+    loop {
+        let (len, _) = socket.recv_from(&mut buff)?;
+        for addr in resolve_instances(&buff[..len], service_type) {
+            if addr == *connect_to {
+                trace!("saw our own mDNS announcement, this should happen once");
+                continue;
+            }
+            let stream = TcpStream::connect(addr);
+            spawn_callback(stream);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_name_reads_uncompressed_labels() {
+        let mut msg = Vec::new();
+        encode_name(&mut msg, "_myapp._tcp.local");
+        let (name, next) = decode_name(&msg, 0).unwrap();
+        assert_eq!(name, "_myapp._tcp.local");
+        assert_eq!(next, msg.len());
+    }
+
+    #[test]
+    fn decode_name_follows_compression_pointer() {
+        // a target name at offset 0, then a second name that's just a pointer back to it
+        let mut msg = Vec::new();
+        encode_name(&mut msg, "instance.local");
+        let pointer_offset = msg.len();
+        msg.extend_from_slice(&0xc000u16.to_be_bytes());
+
+        let (name, next) = decode_name(&msg, pointer_offset).unwrap();
+        assert_eq!(name, "instance.local");
+        // the offset after a pointer is right after the 2-byte pointer itself, not the target
+        assert_eq!(next, pointer_offset + 2);
+    }
+
+    #[test]
+    fn decode_name_rejects_pointer_loop() {
+        // a pointer at offset 0 that points at itself
+        let msg = vec![0xc0, 0x00];
+        assert_eq!(decode_name(&msg, 0), Err(()));
+    }
+
+    #[test]
+    fn resolve_instances_finds_srv_and_address() {
+        let connect_to: SocketAddr = "192.168.1.5:1337".parse().unwrap();
+        let msg = build_announce("_myapp._tcp.local", "node-a", &connect_to);
+        let found = resolve_instances(&msg, "_myapp._tcp.local");
+        assert_eq!(found, vec![connect_to]);
+    }
+
+    #[test]
+    fn resolve_instances_ignores_other_service_types() {
+        let connect_to: SocketAddr = "192.168.1.5:1337".parse().unwrap();
+        let msg = build_announce("_myapp._tcp.local", "node-a", &connect_to);
+        assert!(resolve_instances(&msg, "_otherapp._tcp.local").is_empty());
+    }
+
+    #[test]
+    fn resolve_instances_returns_empty_on_malformed_message() {
+        assert!(resolve_instances(&[1, 2, 3], "_myapp._tcp.local").is_empty());
+    }
+
+    #[test]
+    fn resolve_instances_drops_srv_record_with_short_rdata() {
+        let service_type = "_myapp._tcp.local";
+        let mut msg = Vec::new();
+        msg.extend_from_slice(&0u16.to_be_bytes()); // id
+        msg.extend_from_slice(&0x8400u16.to_be_bytes()); // flags
+        msg.extend_from_slice(&0u16.to_be_bytes()); // qdcount
+        msg.extend_from_slice(&1u16.to_be_bytes()); // ancount
+        msg.extend_from_slice(&0u16.to_be_bytes()); // nscount
+        msg.extend_from_slice(&0u16.to_be_bytes()); // arcount
+
+        encode_name(&mut msg, &format!("node-a.{}", service_type));
+        msg.extend_from_slice(&TYPE_SRV.to_be_bytes());
+        msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+        msg.extend_from_slice(&120u32.to_be_bytes());
+        msg.extend_from_slice(&0u16.to_be_bytes()); // rdlength: 0, too short for a real SRV record
+
+        // must not panic, and a record this malformed yields no resolved address
+        assert!(resolve_instances(&msg, service_type).is_empty());
+    }
+}
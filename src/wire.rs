@@ -0,0 +1,313 @@
+//! The wire format sent on the `Broadcast`/`Multicast` discovery methods.
+//!
+//! Frame layout, all integers big-endian:
+//!
+//! ```text
+//! byte 0      magic/version byte (FRAME_MAGIC_V1, or FRAME_MAGIC_V1_HMAC if HMAC-authenticated)
+//! bytes 1-2   cluster ID (u16) -- packets whose cluster ID doesn't match ours are ignored, so
+//!             two unrelated applications sharing a multicast group don't try to connect to each other
+//! byte 3      address family: 4 or 6
+//! bytes 4..   address (4 or 16 bytes) followed by port (u16)
+//! if authenticated:
+//!   8 bytes   unix timestamp, seconds (u64), when the announcement was sent
+//!   16 bytes  HMAC-SHA256 (truncated), keyed by the shared secret, over the address/port/timestamp bytes above
+//! remaining   zero or more TLV-encoded metadata entries: tag (u8), length (u8), value
+//! ```
+use std::convert::TryInto;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Identifies this frame layout; packets starting with any other byte (including the bare 6/18-byte
+/// packets emitted by versions of this crate prior to the framed format) are dropped as unrecognized.
+const FRAME_MAGIC_V1: u8 = 0xAD;
+/// Like `FRAME_MAGIC_V1`, but the address/port are followed by a timestamp and HMAC tag; see
+/// [`encode`] and [`decode`].
+const FRAME_MAGIC_V1_HMAC: u8 = 0xAE;
+
+const HMAC_TAG_LEN: usize = 16;
+/// Timestamps further from our clock than this, in either direction, are treated as stale/invalid
+/// rather than as proof the sender holds the shared secret.
+const MAX_TIMESTAMP_SKEW_SECS: u64 = 30;
+
+const TAG_UUID: u8 = 1;
+const TAG_NAME: u8 = 2;
+const TAG_CAPABILITY: u8 = 3;
+
+/// Metadata a peer announces about itself alongside its connect-back address: an optional node
+/// UUID, an optional short human-readable name, and a list of advertised capability strings. All
+/// fields are optional/empty by default, and unrecognized TLV tags in a received frame are ignored
+/// so the format can grow further without breaking older peers.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metadata {
+    pub uuid: Option<[u8; 16]>,
+    pub name: Option<String>,
+    pub capabilities: Vec<String>,
+}
+
+impl Metadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_uuid(mut self, uuid: [u8; 16]) -> Self {
+        self.uuid = Some(uuid);
+        self
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn with_capability(mut self, capability: impl Into<String>) -> Self {
+        self.capabilities.push(capability.into());
+        self
+    }
+
+    fn encode_tlv(&self, buff: &mut Vec<u8>) {
+        if let Some(uuid) = &self.uuid {
+            buff.push(TAG_UUID);
+            buff.push(uuid.len() as u8);
+            buff.extend_from_slice(uuid);
+        }
+        if let Some(name) = &self.name {
+            let bytes = name.as_bytes();
+            let len = bytes.len().min(u8::MAX as usize);
+            buff.push(TAG_NAME);
+            buff.push(len as u8);
+            buff.extend_from_slice(&bytes[..len]);
+        }
+        for capability in &self.capabilities {
+            let bytes = capability.as_bytes();
+            let len = bytes.len().min(u8::MAX as usize);
+            buff.push(TAG_CAPABILITY);
+            buff.push(len as u8);
+            buff.extend_from_slice(&bytes[..len]);
+        }
+    }
+
+    fn decode_tlv(buff: &[u8]) -> Metadata {
+        let mut metadata = Metadata::default();
+        let mut offset = 0;
+        while offset + 2 <= buff.len() {
+            let tag = buff[offset];
+            let len = buff[offset + 1] as usize;
+            let start = offset + 2;
+            if start + len > buff.len() {
+                break;
+            }
+            let value = &buff[start..start + len];
+            match tag {
+                TAG_UUID if len == 16 => {
+                    metadata.uuid = value.try_into().ok();
+                },
+                TAG_NAME => {
+                    metadata.name = Some(String::from_utf8_lossy(value).into_owned());
+                },
+                TAG_CAPABILITY => {
+                    metadata.capabilities.push(String::from_utf8_lossy(value).into_owned());
+                },
+                _ => {
+                    // unrecognized tag; skip it so newer senders can add fields without breaking us
+                },
+            }
+            offset = start + len;
+        }
+        metadata
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// Computes the truncated HMAC-SHA256 tag over `signed_bytes` (the address/port/timestamp bytes),
+/// keyed by `secret`.
+fn hmac_tag(secret: &[u8], signed_bytes: &[u8]) -> [u8; HMAC_TAG_LEN] {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts a key of any size");
+    mac.update(signed_bytes);
+    let full = mac.finalize().into_bytes();
+    let mut tag = [0u8; HMAC_TAG_LEN];
+    tag.copy_from_slice(&full[..HMAC_TAG_LEN]);
+    tag
+}
+
+/// Constant-time byte-slice comparison, so an invalid tag can't be brute-forced byte-by-byte via timing.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Encodes `connect_to` and `metadata` into a frame announcing membership in `cluster_id`. If
+/// `secret` is provided, the frame is HMAC-authenticated: peers that don't configure the same
+/// secret will reject it, and peers that do will reject any unauthenticated frame in return.
+pub(crate) fn encode(cluster_id: u16, connect_to: &SocketAddr, metadata: &Metadata, secret: Option<&[u8]>) -> Vec<u8> {
+    let mut buff = vec![if secret.is_some() { FRAME_MAGIC_V1_HMAC } else { FRAME_MAGIC_V1 }];
+    buff.extend_from_slice(&cluster_id.to_be_bytes());
+    let addr_start = buff.len();
+    match connect_to {
+        SocketAddr::V4(addr) => {
+            buff.push(4);
+            buff.extend_from_slice(&addr.ip().octets());
+        },
+        SocketAddr::V6(addr) => {
+            buff.push(6);
+            buff.extend_from_slice(&addr.ip().octets());
+        },
+    }
+    buff.extend_from_slice(&connect_to.port().to_be_bytes());
+    if let Some(secret) = secret {
+        buff.extend_from_slice(&unix_timestamp().to_be_bytes());
+        let tag = hmac_tag(secret, &buff[addr_start..]);
+        buff.extend_from_slice(&tag);
+    }
+    metadata.encode_tlv(&mut buff);
+    buff
+}
+
+/// Decodes a frame, rejecting it if the magic/version byte is unrecognized, the cluster ID doesn't
+/// match `cluster_id`, or (when `secret` is set) the HMAC tag is missing, invalid, or stale. A
+/// `secret` of `None` only accepts unauthenticated frames; `Some` only accepts authenticated ones
+/// whose tag verifies.
+pub(crate) fn decode(cluster_id: u16, secret: Option<&[u8]>, buff: &[u8]) -> Result<(SocketAddr, Metadata), ()> {
+    let authenticated = match (buff.first(), secret.is_some()) {
+        (Some(&FRAME_MAGIC_V1), false) => false,
+        (Some(&FRAME_MAGIC_V1_HMAC), true) => true,
+        _ => return Err(()),
+    };
+    let frame_cluster_id = u16::from_be_bytes(buff.get(1..3).ok_or(())?.try_into().unwrap());
+    if frame_cluster_id != cluster_id {
+        return Err(());
+    }
+    let addr_start = 3;
+    let family = *buff.get(addr_start).ok_or(())?;
+    let (ip, rest_offset) = match family {
+        4 => {
+            let octets: [u8; 4] = buff.get(4..8).ok_or(())?.try_into().unwrap();
+            (IpAddr::V4(octets.into()), 8)
+        },
+        6 => {
+            let octets: [u8; 16] = buff.get(4..20).ok_or(())?.try_into().unwrap();
+            (IpAddr::V6(octets.into()), 20)
+        },
+        _ => return Err(()),
+    };
+    let port = u16::from_be_bytes(buff.get(rest_offset..rest_offset + 2).ok_or(())?.try_into().unwrap());
+    let mut tlv_start = rest_offset + 2;
+
+    if authenticated {
+        let secret = secret.unwrap();
+        let timestamp_start = tlv_start;
+        let tag_start = timestamp_start + 8;
+        tlv_start = tag_start + HMAC_TAG_LEN;
+        let timestamp = u64::from_be_bytes(buff.get(timestamp_start..tag_start).ok_or(())?.try_into().unwrap());
+        let received_tag = buff.get(tag_start..tlv_start).ok_or(())?;
+        let expected_tag = hmac_tag(secret, &buff[addr_start..timestamp_start + 8]);
+        if !constant_time_eq(&expected_tag, received_tag) {
+            return Err(());
+        }
+        let now = unix_timestamp();
+        if now.abs_diff(timestamp) > MAX_TIMESTAMP_SKEW_SECS {
+            return Err(());
+        }
+    }
+
+    let metadata = Metadata::decode_tlv(buff.get(tlv_start..).ok_or(())?);
+    Ok((SocketAddr::new(ip, port), metadata))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_address_and_metadata() {
+        let connect_to: SocketAddr = "192.168.1.5:1337".parse().unwrap();
+        let metadata = Metadata::new()
+            .with_uuid([7; 16])
+            .with_name("node-a")
+            .with_capability("gpu")
+            .with_capability("ssd");
+        let frame = encode(42, &connect_to, &metadata, None);
+        let (decoded_addr, decoded_metadata) = decode(42, None, &frame).unwrap();
+        assert_eq!(decoded_addr, connect_to);
+        assert_eq!(decoded_metadata, metadata);
+    }
+
+    #[test]
+    fn round_trips_ipv6_address() {
+        let connect_to: SocketAddr = "[fe80::1]:1337".parse().unwrap();
+        let frame = encode(0, &connect_to, &Metadata::default(), None);
+        let (decoded_addr, _) = decode(0, None, &frame).unwrap();
+        assert_eq!(decoded_addr, connect_to);
+    }
+
+    #[test]
+    fn rejects_cluster_id_mismatch() {
+        let connect_to: SocketAddr = "192.168.1.5:1337".parse().unwrap();
+        let frame = encode(1, &connect_to, &Metadata::default(), None);
+        assert_eq!(decode(2, None, &frame), Err(()));
+    }
+
+    #[test]
+    fn rejects_unrecognized_magic_byte() {
+        let mut frame = encode(1, &"192.168.1.5:1337".parse().unwrap(), &Metadata::default(), None);
+        frame[0] = 0xFF;
+        assert_eq!(decode(1, None, &frame), Err(()));
+    }
+
+    #[test]
+    fn hmac_round_trips_with_matching_secret() {
+        let connect_to: SocketAddr = "192.168.1.5:1337".parse().unwrap();
+        let metadata = Metadata::new().with_name("node-a");
+        let frame = encode(1, &connect_to, &metadata, Some(b"shared-secret"));
+        let (decoded_addr, decoded_metadata) = decode(1, Some(b"shared-secret"), &frame).unwrap();
+        assert_eq!(decoded_addr, connect_to);
+        assert_eq!(decoded_metadata, metadata);
+    }
+
+    #[test]
+    fn hmac_rejects_wrong_secret() {
+        let connect_to: SocketAddr = "192.168.1.5:1337".parse().unwrap();
+        let frame = encode(1, &connect_to, &Metadata::default(), Some(b"shared-secret"));
+        assert_eq!(decode(1, Some(b"wrong-secret"), &frame), Err(()));
+    }
+
+    #[test]
+    fn hmac_rejects_tampered_address() {
+        let connect_to: SocketAddr = "192.168.1.5:1337".parse().unwrap();
+        let mut frame = encode(1, &connect_to, &Metadata::default(), Some(b"shared-secret"));
+        // flip a byte in the encoded IPv4 address, leaving the tag (and timestamp) untouched
+        frame[4] ^= 0xFF;
+        assert_eq!(decode(1, Some(b"shared-secret"), &frame), Err(()));
+    }
+
+    #[test]
+    fn hmac_rejects_missing_tag_on_unauthenticated_frame() {
+        let connect_to: SocketAddr = "192.168.1.5:1337".parse().unwrap();
+        let frame = encode(1, &connect_to, &Metadata::default(), None);
+        assert_eq!(decode(1, Some(b"shared-secret"), &frame), Err(()));
+    }
+
+    #[test]
+    fn hmac_rejects_stale_timestamp() {
+        let connect_to: SocketAddr = "192.168.1.5:1337".parse().unwrap();
+        let secret = b"shared-secret";
+        let mut frame = encode(1, &connect_to, &Metadata::default(), Some(secret));
+        let addr_start = 3;
+        let timestamp_start = frame.len() - HMAC_TAG_LEN - 8;
+        let tag_start = timestamp_start + 8;
+        let stale = unix_timestamp() - MAX_TIMESTAMP_SKEW_SECS - 1;
+        frame[timestamp_start..tag_start].copy_from_slice(&stale.to_be_bytes());
+        let tag = hmac_tag(secret, &frame[addr_start..tag_start]);
+        frame[tag_start..tag_start + HMAC_TAG_LEN].copy_from_slice(&tag);
+        assert_eq!(decode(1, Some(secret), &frame), Err(()));
+    }
+}